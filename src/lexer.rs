@@ -1,60 +1,102 @@
+/// A byte-offset range into the original input, used to locate a token or
+/// error for diagnostics.
+pub(crate) type Span = std::ops::Range<usize>;
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub(crate) enum LexItem<'a> {
-    At(&'a str),
-    Paren(char),
-    Word(&'a str),
-    Whitespace(&'a str),
-    NewLine,
+    At(&'a str, Span),
+    Paren(char, Span),
+    Word(&'a str, Span),
+    Whitespace(&'a str, Span),
+    NewLine(Span),
 }
 
 impl<'a> LexItem<'a> {
     pub(crate) fn push_to(&self, acc: &mut String) {
         match self {
-            LexItem::At(w) => acc.push_str(w),
-            LexItem::Paren(w) => acc.push(*w),
-            LexItem::Word(w) => acc.push_str(w),
-            LexItem::Whitespace(w) => acc.push_str(w),
-            LexItem::NewLine => acc.push('\n'),
+            LexItem::At(w, _) => acc.push_str(w),
+            LexItem::Paren(w, _) => acc.push(*w),
+            LexItem::Word(w, _) => acc.push_str(w),
+            LexItem::Whitespace(w, _) => acc.push_str(w),
+            LexItem::NewLine(_) => acc.push('\n'),
+        }
+    }
+
+    pub(crate) fn span(&self) -> Span {
+        match self {
+            LexItem::At(_, span)
+            | LexItem::Paren(_, span)
+            | LexItem::Word(_, span)
+            | LexItem::Whitespace(_, span)
+            | LexItem::NewLine(span) => span.clone(),
         }
     }
 }
 
+/// Converts a byte offset into `input` to a 1-based `(line, column)` pair,
+/// so a `span` produced by [`lex`] can be turned into a human-readable
+/// location for diagnostics.
+pub(crate) fn line_col(input: &str, offset: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut column = 1;
+
+    for c in input[..offset.min(input.len())].chars() {
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+
+    (line, column)
+}
+
 pub(crate) fn lex(input: &str) -> Vec<LexItem<'_>> {
     let mut result = vec![];
     let mut start_index = 0;
 
     for (index, c) in input.char_indices() {
+        let end_index = index + c.len_utf8();
+
         match c {
             '@' => {
-                result.push(LexItem::At(&input[index..index + c.len_utf8()]));
+                result.push(LexItem::At(&input[index..end_index], index..end_index));
             }
             '\\' => match result.last_mut() {
-                Some(LexItem::At(v)) if *v == "\\" => {
-                    *v = &input[start_index..index + c.len_utf8()];
+                Some(LexItem::At(v, span)) if *v == "\\" => {
+                    *v = &input[start_index..end_index];
+                    span.end = end_index;
                 }
                 _ => {
                     start_index = index;
-                    result.push(LexItem::At(&input[index..index + c.len_utf8()]));
+                    result.push(LexItem::At(&input[index..end_index], index..end_index));
                 }
             },
             '{' | '}' => {
-                result.push(LexItem::Paren(c));
+                result.push(LexItem::Paren(c, index..end_index));
             }
             ' ' | '\t' => match result.last_mut() {
-                Some(LexItem::Whitespace(v)) => *v = &input[start_index..index + c.len_utf8()],
+                Some(LexItem::Whitespace(v, span)) => {
+                    *v = &input[start_index..end_index];
+                    span.end = end_index;
+                }
                 _ => {
                     start_index = index;
-                    result.push(LexItem::Whitespace(&input[index..index + c.len_utf8()]));
+                    result.push(LexItem::Whitespace(&input[index..end_index], index..end_index));
                 }
             },
             '\n' => {
-                result.push(LexItem::NewLine);
+                result.push(LexItem::NewLine(index..end_index));
             }
             _ => match result.last_mut() {
-                Some(LexItem::Word(v)) => *v = &input[start_index..index + c.len_utf8()],
+                Some(LexItem::Word(v, span)) => {
+                    *v = &input[start_index..end_index];
+                    span.end = end_index;
+                }
                 _ => {
                     start_index = index;
-                    result.push(LexItem::Word(&input[index..index + c.len_utf8()]))
+                    result.push(LexItem::Word(&input[index..end_index], index..end_index))
                 }
             },
         }
@@ -73,12 +115,12 @@ mod test {
         assert_eq!(
             result,
             vec![
-                LexItem::At("@"),
-                LexItem::Word("name"),
-                LexItem::Whitespace(" "),
-                LexItem::Word("Memory"),
-                LexItem::Whitespace(" "),
-                LexItem::Word("Management")
+                LexItem::At("@", 0..1),
+                LexItem::Word("name", 1..5),
+                LexItem::Whitespace(" ", 5..6),
+                LexItem::Word("Memory", 6..12),
+                LexItem::Whitespace(" ", 12..13),
+                LexItem::Word("Management", 13..23)
             ]
         );
 
@@ -86,12 +128,12 @@ mod test {
         assert_eq!(
             result,
             vec![
-                LexItem::At("\\"),
-                LexItem::Word("name"),
-                LexItem::Whitespace(" "),
-                LexItem::Word("Memory"),
-                LexItem::Whitespace(" "),
-                LexItem::Word("Management")
+                LexItem::At("\\", 0..1),
+                LexItem::Word("name", 1..5),
+                LexItem::Whitespace(" ", 5..6),
+                LexItem::Word("Memory", 6..12),
+                LexItem::Whitespace(" ", 12..13),
+                LexItem::Word("Management", 13..23)
             ]
         );
 
@@ -99,12 +141,12 @@ mod test {
         assert_eq!(
             result,
             vec![
-                LexItem::At("\\\\"),
-                LexItem::Word("name"),
-                LexItem::Whitespace(" "),
-                LexItem::Word("Memory"),
-                LexItem::Whitespace(" "),
-                LexItem::Word("Management")
+                LexItem::At("\\\\", 0..2),
+                LexItem::Word("name", 2..6),
+                LexItem::Whitespace(" ", 6..7),
+                LexItem::Word("Memory", 7..13),
+                LexItem::Whitespace(" ", 13..14),
+                LexItem::Word("Management", 14..24)
             ]
         );
     }
@@ -115,21 +157,29 @@ mod test {
         assert_eq!(
             result,
             vec![
-                LexItem::At("@"),
-                LexItem::Paren('{'),
-                LexItem::NewLine,
-                LexItem::Word("*"),
-                LexItem::Whitespace(" "),
-                LexItem::At("@"),
-                LexItem::Word("name"),
-                LexItem::Whitespace(" "),
-                LexItem::Word("Memory"),
-                LexItem::Whitespace(" "),
-                LexItem::Word("Management"),
-                LexItem::NewLine,
-                LexItem::At("@"),
-                LexItem::Paren('}')
+                LexItem::At("@", 0..1),
+                LexItem::Paren('{', 1..2),
+                LexItem::NewLine(2..3),
+                LexItem::Word("*", 3..4),
+                LexItem::Whitespace(" ", 4..5),
+                LexItem::At("@", 5..6),
+                LexItem::Word("name", 6..10),
+                LexItem::Whitespace(" ", 10..11),
+                LexItem::Word("Memory", 11..17),
+                LexItem::Whitespace(" ", 17..18),
+                LexItem::Word("Management", 18..28),
+                LexItem::NewLine(28..29),
+                LexItem::At("@", 29..30),
+                LexItem::Paren('}', 30..31)
             ]
         );
     }
+
+    #[test]
+    fn line_col_tracks_newlines() {
+        let input = "@name Memory\nManagement";
+        assert_eq!(line_col(input, 0), (1, 1));
+        assert_eq!(line_col(input, 6), (1, 7));
+        assert_eq!(line_col(input, 13), (2, 1));
+    }
 }
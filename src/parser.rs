@@ -1,15 +1,16 @@
-use crate::lexer::{lex, LexItem};
+use crate::lexer::{lex, LexItem, Span};
 
 const OPEN_PAREN: char = '{';
 const CLOSED_PAREN: char = '}';
 
 #[derive(Debug, Clone)]
 pub enum ParseError {
-    UnexpectedEndOfInput,
     UnexpectedInput {
         found: String,
         expected: Vec<String>,
+        span: Span,
     },
+    UnexpectedEndOfInput,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -19,26 +20,196 @@ pub(crate) enum GrammarItem<'a> {
         params: Vec<&'a str>,
         tag: &'a str,
     },
+    Inline(Inline<'a>),
+    /// `@link <target>`; the text up to the matching [`GrammarItem::LinkEnd`]
+    /// is the link's display text, rather than a `@ref`'s bare word.
+    LinkStart {
+        target: &'a str,
+    },
+    LinkEnd,
     Text(String),
     GroupStart,
     GroupEnd,
 }
 
+/// A single-word inline command, distinguished by the styling it implies
+/// rather than lumped into a generic [`GrammarItem::Notation`] like
+/// `@sa`/`@see` are.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Inline<'a> {
+    /// `@a`, `@e`, `@em` - emphasize (italicize) a single word.
+    Emphasis(&'a str),
+    /// `@b` - bold a single word.
+    Bold(&'a str),
+    /// `@c`, `@p` - render a single word in monospace.
+    Monospace(&'a str),
+    /// `@ref` - cross-reference to another symbol.
+    Ref(&'a str),
+}
+
 enum ParamParser {
     None,
     Whitespace,
     Paren,
 }
 
+/// Doxygen verbatim environments: `(open tag, end tag, relex)` triples.
+/// Everything between the open and end tag is copied into a single `Text`
+/// node untouched by word/whitespace collapsing, instead of being lexed
+/// and reformatted like regular description text. `relex` marks
+/// environments whose inner content should still be re-lexed as commands
+/// rather than copied verbatim; none of the built-in environments need
+/// this today, but the table leaves room for one that does.
+const VERBATIM_ENVIRONMENTS: &[(&str, &str, bool)] = &[
+    ("code", "endcode", false),
+    ("verbatim", "endverbatim", false),
+    ("dot", "enddot", false),
+    ("msc", "endmsc", false),
+    ("htmlonly", "endhtmlonly", false),
+    ("f$", "f$", false),
+    ("f[", "f]", false),
+];
+
+fn verbatim_end_tag(tag: &str) -> Option<&'static str> {
+    VERBATIM_ENVIRONMENTS
+        .iter()
+        .find(|(start, _, relex)| *start == tag && !*relex)
+        .map(|(_, end, _)| *end)
+}
+
+/// A node in the nested representation of a doxygen comment, built by
+/// [`parse_tree`] out of the flat [`GrammarItem`] stream produced by
+/// [`parse`]. Unlike `GrammarItem`, a `Group` owns the nodes it contains
+/// instead of leaving `GroupStart`/`GroupEnd` as markers for the consumer
+/// to re-balance, and a command's trailing description is carried on the
+/// command node itself rather than as a loose sibling.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum DoxNode<'a> {
+    Notation {
+        meta: Vec<&'a str>,
+        params: Vec<&'a str>,
+        tag: &'a str,
+        text: String,
+    },
+    Inline(Inline<'a>),
+    /// `@link <target> ... @endlink`, with `content` holding the nested
+    /// display text/nodes between the two tags.
+    Link {
+        target: &'a str,
+        content: Vec<DoxNode<'a>>,
+    },
+    Text(String),
+    Group(Vec<DoxNode<'a>>),
+}
+
 pub(crate) fn parse(input: &str) -> Result<Vec<GrammarItem<'_>>, ParseError> {
     let lexed = lex(input);
-    parse_items(lexed)
+    let (items, mut errors) = parse_items(lexed, false);
+
+    match errors.pop() {
+        Some(err) => Err(err),
+        None => Ok(items),
+    }
+}
+
+/// Like [`parse`], but never bails out on the first malformed command.
+/// Each `UnexpectedInput` is recorded and the offending token is kept as
+/// literal `Text` instead, so a whole file of comments can still produce
+/// best-effort output even when a few commands are malformed.
+pub(crate) fn parse_recovering(input: &str) -> (Vec<GrammarItem<'_>>, Vec<ParseError>) {
+    let lexed = lex(input);
+    parse_items(lexed, true)
 }
 
-fn parse_items(input: Vec<LexItem>) -> Result<Vec<GrammarItem<'_>>, ParseError> {
+pub(crate) fn parse_tree(input: &str) -> Result<Vec<DoxNode<'_>>, ParseError> {
+    let items = parse(input)?;
+    build_tree(items)
+}
+
+fn build_tree(items: Vec<GrammarItem<'_>>) -> Result<Vec<DoxNode<'_>>, ParseError> {
+    // The frame's `Option<&str>` is the link target when the frame was
+    // opened by `@link`, or `None` for a plain `@{ ... @}` group.
+    let mut stack: Vec<(Option<&str>, Vec<DoxNode<'_>>)> = vec![(None, vec![])];
+    let mut iter = items.into_iter().peekable();
+
+    while let Some(item) = iter.next() {
+        match item {
+            GrammarItem::GroupStart => stack.push((None, vec![])),
+            GrammarItem::GroupEnd => {
+                if stack.len() > 1 {
+                    let (target, children) = stack.pop().unwrap();
+                    let node = match target {
+                        Some(target) => DoxNode::Link {
+                            target,
+                            content: children,
+                        },
+                        None => DoxNode::Group(children),
+                    };
+                    stack.last_mut().unwrap().1.push(node);
+                }
+            }
+            GrammarItem::LinkStart { target } => stack.push((Some(target), vec![])),
+            GrammarItem::LinkEnd => {
+                if stack.len() > 1 {
+                    let (target, content) = stack.pop().unwrap();
+                    let node = match target {
+                        Some(target) => DoxNode::Link { target, content },
+                        None => DoxNode::Group(content),
+                    };
+                    stack.last_mut().unwrap().1.push(node);
+                }
+            }
+            GrammarItem::Notation { meta, params, tag } => {
+                let text = match iter.peek() {
+                    Some(GrammarItem::Text(_)) => match iter.next() {
+                        Some(GrammarItem::Text(text)) => text,
+                        _ => unreachable!(),
+                    },
+                    _ => String::new(),
+                };
+
+                stack.last_mut().unwrap().1.push(DoxNode::Notation {
+                    meta,
+                    params,
+                    tag,
+                    text,
+                });
+            }
+            GrammarItem::Inline(inline) => {
+                stack.last_mut().unwrap().1.push(DoxNode::Inline(inline))
+            }
+            GrammarItem::Text(text) => stack.last_mut().unwrap().1.push(DoxNode::Text(text)),
+        }
+    }
+
+    if stack.len() > 1 {
+        return Err(ParseError::UnexpectedEndOfInput);
+    }
+
+    Ok(stack.pop().unwrap().1)
+}
+
+/// Pushes `text` onto the last `Text` node, or starts a new one, matching
+/// the merging every other branch of [`parse_items`] already does for
+/// literal text.
+fn push_text(grammar_items: &mut Vec<GrammarItem<'_>>, text: String) {
+    match grammar_items.last_mut() {
+        Some(GrammarItem::Text(existing)) => existing.push_str(&text),
+        _ => grammar_items.push(GrammarItem::Text(text)),
+    }
+}
+
+fn parse_items(input: Vec<LexItem>, recover: bool) -> (Vec<GrammarItem<'_>>, Vec<ParseError>) {
     let mut grammar_items = vec![];
+    let mut errors = vec![];
     let mut param_iter_skip_count = 0;
 
+    // Tracks the end tag of the verbatim environment we are currently inside
+    // (if any), so a command whose open and end tag are the same word (e.g.
+    // `@f$ ... @f$`) is only ever treated as "closing" once, rather than
+    // immediately reopening the environment it just closed.
+    let mut verbatim_end: Option<&'static str> = None;
+
     for (index, current) in input.iter().enumerate() {
         let rest = &input[index..];
         let next = rest.get(1);
@@ -48,43 +219,61 @@ fn parse_items(input: Vec<LexItem>) -> Result<Vec<GrammarItem<'_>>, ParseError>
             continue;
         }
 
-        // Do not do any formatting inside of code blocks
-        let ends_code = matches!(current, LexItem::At(_))
-            && matches!(next, Some(LexItem::Word(v)) if *v == "endcode");
-        if !ends_code {
-            match &mut grammar_items[..] {
-                [.., GrammarItem::Notation { tag, .. }] if *tag == "code" => {
-                    let mut text = String::new();
-                    current.push_to(&mut text);
-
-                    grammar_items.push(GrammarItem::Text(text));
-                    continue;
-                }
-                [.., GrammarItem::Notation { tag, .. }, GrammarItem::Text(text)]
-                    if *tag == "code" =>
-                {
-                    current.push_to(text);
-                    continue;
+        // Do not do any formatting inside of verbatim environments (@code,
+        // @verbatim, @f$, @dot, ...) until their matching end tag is seen.
+        let closes_verbatim = matches!(verbatim_end, Some(end_tag) if matches!(current, LexItem::At(_, _))
+            && matches!(next, Some(LexItem::Word(v, _)) if *v == end_tag));
+
+        if verbatim_end.is_some() {
+            if closes_verbatim {
+                verbatim_end = None;
+            } else {
+                match &mut grammar_items[..] {
+                    [.., GrammarItem::Notation { .. }] => {
+                        let mut text = String::new();
+                        current.push_to(&mut text);
+
+                        grammar_items.push(GrammarItem::Text(text));
+                        continue;
+                    }
+                    [.., GrammarItem::Notation { .. }, GrammarItem::Text(text)] => {
+                        current.push_to(text);
+                        continue;
+                    }
+                    _ => {}
                 }
-                _ => {}
             }
         }
 
         match current {
-            LexItem::At(_) => {
+            LexItem::At(_, _) => {
                 if let Some(next) = next {
                     match next {
-                        LexItem::Paren(v) => match *v {
+                        LexItem::Paren(v, paren_span) => match *v {
                             OPEN_PAREN => grammar_items.push(GrammarItem::GroupStart),
                             CLOSED_PAREN => grammar_items.push(GrammarItem::GroupEnd),
                             _ => {
-                                return Err(ParseError::UnexpectedInput {
+                                let err = ParseError::UnexpectedInput {
                                     found: v.to_string(),
                                     expected: vec![OPEN_PAREN.into(), CLOSED_PAREN.into()],
-                                })
+                                    span: current.span().start..paren_span.end,
+                                };
+
+                                if !recover {
+                                    return (grammar_items, vec![err]);
+                                }
+
+                                errors.push(err);
+
+                                let mut text = String::new();
+                                current.push_to(&mut text);
+                                text.push(*v);
+                                push_text(&mut grammar_items, text);
+
+                                param_iter_skip_count = 1;
                             }
                         },
-                        LexItem::Word(v) => {
+                        LexItem::Word(v, word_span) => {
                             let mut meta = vec![];
                             let tag;
 
@@ -99,11 +288,26 @@ fn parse_items(input: Vec<LexItem>) -> Result<Vec<GrammarItem<'_>>, ParseError>
                                         meta.push("out");
                                     }
                                     None => {}
-                                    Some((_, v)) => {
-                                        return Err(ParseError::UnexpectedInput {
-                                            found: v.to_string(),
+                                    Some((_, bad)) => {
+                                        let err = ParseError::UnexpectedInput {
+                                            found: bad.to_string(),
                                             expected: vec!["in]".into(), "out]".into()],
-                                        })
+                                            span: word_span.clone(),
+                                        };
+
+                                        if !recover {
+                                            return (grammar_items, vec![err]);
+                                        }
+
+                                        errors.push(err);
+
+                                        let mut text = String::new();
+                                        current.push_to(&mut text);
+                                        text.push_str(v);
+                                        push_text(&mut grammar_items, text);
+
+                                        param_iter_skip_count = 1;
+                                        continue;
                                     }
                                 }
 
@@ -115,7 +319,9 @@ fn parse_items(input: Vec<LexItem>) -> Result<Vec<GrammarItem<'_>>, ParseError>
                                     "a" | "b" | "c" | "p" | "emoji" | "e" | "em" | "def"
                                     | "class" | "category" | "concept" | "enum" | "example"
                                     | "extends" | "file" | "sa" | "see" | "retval"
-                                    | "exception" | "throw" | "throws" => ParamParser::Whitespace,
+                                    | "exception" | "throw" | "throws" | "ref" | "link" => {
+                                        ParamParser::Whitespace
+                                    }
                                     "code" => ParamParser::Paren,
                                     _ => ParamParser::None,
                                 };
@@ -127,13 +333,13 @@ fn parse_items(input: Vec<LexItem>) -> Result<Vec<GrammarItem<'_>>, ParseError>
                                     .iter()
                                     .enumerate()
                                     .skip(2)
-                                    .find(|(_, next)| !matches!(next, LexItem::Whitespace(_)))
+                                    .find(|(_, next)| !matches!(next, LexItem::Whitespace(_, _)))
                                     .and_then(|(skip, next)| match next {
-                                        LexItem::Word(word) => Some((skip, *word)),
+                                        LexItem::Word(word, _) => Some((skip, *word)),
                                         _ => None,
                                     }),
                                 ParamParser::Paren => match &rest {
-                                    [_, _, LexItem::Paren('{'), LexItem::Word(word), LexItem::Paren('}'), ..] => {
+                                    [_, _, LexItem::Paren('{', _), LexItem::Word(word, _), LexItem::Paren('}', _), ..] => {
                                         Some((4, *word))
                                     }
                                     _ => None,
@@ -148,34 +354,59 @@ fn parse_items(input: Vec<LexItem>) -> Result<Vec<GrammarItem<'_>>, ParseError>
                                 vec![]
                             };
 
-                            grammar_items.push(GrammarItem::Notation { meta, params, tag });
+                            match (tag, params.first().copied()) {
+                                ("a", Some(word)) | ("e", Some(word)) | ("em", Some(word)) => {
+                                    grammar_items.push(GrammarItem::Inline(Inline::Emphasis(word)));
+                                }
+                                ("b", Some(word)) => {
+                                    grammar_items.push(GrammarItem::Inline(Inline::Bold(word)));
+                                }
+                                ("c", Some(word)) | ("p", Some(word)) => {
+                                    grammar_items.push(GrammarItem::Inline(Inline::Monospace(word)));
+                                }
+                                ("ref", Some(word)) => {
+                                    grammar_items.push(GrammarItem::Inline(Inline::Ref(word)));
+                                }
+                                ("link", Some(target)) => {
+                                    grammar_items.push(GrammarItem::LinkStart { target });
+                                }
+                                ("endlink", _) => {
+                                    grammar_items.push(GrammarItem::LinkEnd);
+                                }
+                                _ => {
+                                    grammar_items.push(GrammarItem::Notation { meta, params, tag });
 
-                            if tag == "endcode" {
-                                grammar_items.push(GrammarItem::Text("".into()));
+                                    if closes_verbatim {
+                                        grammar_items.push(GrammarItem::Text("".into()));
+                                    } else if let Some(end_tag) = verbatim_end_tag(tag) {
+                                        verbatim_end = Some(end_tag);
+                                    }
+                                }
                             }
                         }
                         _ => {}
                     }
                 }
             }
-            LexItem::Word(v) => match grammar_items.last_mut() {
+            LexItem::Word(v, _) => match grammar_items.last_mut() {
                 Some(GrammarItem::Text(text)) => text.push_str(v),
                 _ => grammar_items.push(GrammarItem::Text(v.to_string())),
             },
-            LexItem::Whitespace(_) => match grammar_items.last_mut() {
+            LexItem::Whitespace(_, _) => match grammar_items.last_mut() {
                 Some(GrammarItem::Text(text)) => text.push(' '),
                 Some(GrammarItem::Notation { params, .. }) if !params.is_empty() => {
                     grammar_items.push(GrammarItem::Text(" ".into()))
                 }
+                Some(GrammarItem::Inline(_)) => grammar_items.push(GrammarItem::Text(" ".into())),
                 None => grammar_items.push(GrammarItem::Text(" ".into())),
                 _ => grammar_items.push(GrammarItem::Text("".into())),
             },
-            LexItem::NewLine => {
+            LexItem::NewLine(_) => {
                 if let Some(GrammarItem::Text(text)) = grammar_items.last_mut() {
                     text.push('\n');
                 }
             }
-            LexItem::Paren(v) => {
+            LexItem::Paren(v, _) => {
                 if let Some(GrammarItem::Text(text)) = grammar_items.last_mut() {
                     text.push(*v);
                 }
@@ -183,7 +414,7 @@ fn parse_items(input: Vec<LexItem>) -> Result<Vec<GrammarItem<'_>>, ParseError>
         }
     }
 
-    Ok(grammar_items)
+    (grammar_items, errors)
 }
 
 #[cfg(test)]
@@ -368,4 +599,199 @@ mod test {
             ]
         )
     }
+
+    #[test]
+    pub fn verbatim_block() {
+        let result = parse("@verbatim\nraw    text\n@endverbatim").unwrap();
+
+        assert_eq!(
+            result,
+            vec![
+                GrammarItem::Notation {
+                    meta: vec![],
+                    params: vec![],
+                    tag: "verbatim",
+                },
+                GrammarItem::Text("\nraw    text\n".into()),
+                GrammarItem::Notation {
+                    meta: vec![],
+                    params: vec![],
+                    tag: "endverbatim",
+                },
+                GrammarItem::Text("".into())
+            ]
+        )
+    }
+
+    #[test]
+    pub fn inline_math_block_does_not_reopen_after_closing() {
+        let result = parse("@f$ x^2  y @f$ done").unwrap();
+
+        assert_eq!(
+            result,
+            vec![
+                GrammarItem::Notation {
+                    meta: vec![],
+                    params: vec![],
+                    tag: "f$",
+                },
+                GrammarItem::Text(" x^2  y ".into()),
+                GrammarItem::Notation {
+                    meta: vec![],
+                    params: vec![],
+                    tag: "f$",
+                },
+                GrammarItem::Text(" done".into())
+            ]
+        )
+    }
+
+    #[test]
+    pub fn dot_block() {
+        let result = parse("@dot\ndigraph {a -> b}\n@enddot").unwrap();
+
+        assert_eq!(
+            result,
+            vec![
+                GrammarItem::Notation {
+                    meta: vec![],
+                    params: vec![],
+                    tag: "dot",
+                },
+                GrammarItem::Text("\ndigraph {a -> b}\n".into()),
+                GrammarItem::Notation {
+                    meta: vec![],
+                    params: vec![],
+                    tag: "enddot",
+                },
+                GrammarItem::Text("".into())
+            ]
+        )
+    }
+
+    #[test]
+    pub fn unexpected_input_reports_span() {
+        let err = parse("@param[foo] random text").unwrap_err();
+        match err {
+            ParseError::UnexpectedInput { found, span, .. } => {
+                assert_eq!(found, "foo]");
+                assert_eq!(span, 1..11);
+            }
+            ParseError::UnexpectedEndOfInput => panic!("unexpected error variant"),
+        }
+    }
+
+    #[test]
+    pub fn recovering_keeps_malformed_command_as_text_and_continues() {
+        let (items, errors) = parse_recovering("@param[foo] random text, then @name ok");
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            ParseError::UnexpectedInput { ref found, .. } if found == "foo]"
+        ));
+
+        assert_eq!(
+            items,
+            vec![
+                GrammarItem::Text("@param[foo] random text, then ".into()),
+                GrammarItem::Notation {
+                    meta: vec![],
+                    params: vec![],
+                    tag: "name",
+                },
+                GrammarItem::Text("ok".into())
+            ]
+        );
+    }
+
+    #[test]
+    pub fn inline_styled_words() {
+        let result = parse("@a word1 is @b word2 is @c word3 is @e word4 is @ref Symbol").unwrap();
+        assert_eq!(
+            result,
+            vec![
+                GrammarItem::Inline(Inline::Emphasis("word1")),
+                GrammarItem::Text(" is ".into()),
+                GrammarItem::Inline(Inline::Bold("word2")),
+                GrammarItem::Text(" is ".into()),
+                GrammarItem::Inline(Inline::Monospace("word3")),
+                GrammarItem::Text(" is ".into()),
+                GrammarItem::Inline(Inline::Emphasis("word4")),
+                GrammarItem::Text(" is ".into()),
+                GrammarItem::Inline(Inline::Ref("Symbol")),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn p_is_an_alias_for_monospace() {
+        let result = parse("@p word").unwrap();
+        assert_eq!(
+            result,
+            vec![GrammarItem::Inline(Inline::Monospace("word"))]
+        );
+    }
+
+    #[test]
+    pub fn link_wraps_display_text() {
+        let result = parse("@link Foo::bar this function @endlink").unwrap();
+        assert_eq!(
+            result,
+            vec![
+                GrammarItem::LinkStart {
+                    target: "Foo::bar"
+                },
+                GrammarItem::Text("this function ".into()),
+                GrammarItem::LinkEnd
+            ]
+        );
+    }
+
+    #[test]
+    pub fn tree_nests_link_with_target() {
+        let result = parse_tree("@link Foo::bar this function @endlink").unwrap();
+        assert_eq!(
+            result,
+            vec![DoxNode::Link {
+                target: "Foo::bar",
+                content: vec![DoxNode::Text("this function ".into())],
+            }]
+        );
+    }
+
+    #[test]
+    pub fn tree_nests_groups_and_attaches_text() {
+        let result = parse_tree("@{\n* @name Memory Management\n@}").unwrap();
+        assert_eq!(
+            result,
+            vec![DoxNode::Group(vec![
+                DoxNode::Text("* ".into()),
+                DoxNode::Notation {
+                    meta: vec![],
+                    params: vec![],
+                    tag: "name",
+                    text: "Memory Management\n".into(),
+                },
+            ])]
+        );
+    }
+
+    #[test]
+    pub fn tree_reports_unclosed_group() {
+        let err = parse_tree("@{\nX").unwrap_err();
+        assert!(matches!(err, ParseError::UnexpectedEndOfInput));
+    }
+
+    #[test]
+    pub fn link_closed_by_mismatched_group_end_keeps_target() {
+        let result = parse_tree("@link Foo::bar this function @}").unwrap();
+        assert_eq!(
+            result,
+            vec![DoxNode::Link {
+                target: "Foo::bar",
+                content: vec![DoxNode::Text("this function ".into())],
+            }]
+        );
+    }
 }